@@ -0,0 +1,162 @@
+use super::*;
+use super::vector::*;
+use super::line::Line;
+use super::surface::Surface;
+
+// primitive count at which a node stops splitting and becomes a leaf
+const LEAF_SIZE: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+struct Aabb {
+    min: Point,
+    max: Point,
+}
+
+impl Aabb {
+    fn from_surface(surface: &dyn Surface) -> Self {
+        let (min, max) = surface.bounds();
+        Aabb { min, max }
+    }
+
+    fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: component_min(self.min, other.min),
+            max: component_max(self.max, other.max),
+        }
+    }
+
+    fn centroid(&self) -> Point {
+        point!((self.min.x + self.max.x) / 2.0,
+               (self.min.y + self.max.y) / 2.0,
+               (self.min.z + self.max.z) / 2.0)
+    }
+
+    // slab test; returns the entry distance along the ray if it crosses the box
+    fn intersect(&self, ray: &Line) -> Option<f64> {
+        let mut tmin = f64::NEG_INFINITY;
+        let mut tmax = f64::INFINITY;
+        for axis in 0..3 {
+            let origin = ray.origin[axis];
+            let dir = ray.direction[axis];
+            if is_zero(dir) {
+                // axis-parallel ray: the slab is unbounded unless the origin already misses it
+                if origin < self.min[axis] || origin > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (self.min[axis] - origin) / dir;
+            let t2 = (self.max[axis] - origin) / dir;
+            let (t1, t2) = if t1 <= t2 { (t1, t2) } else { (t2, t1) };
+            tmin = tmin.max(t1);
+            tmax = tmax.min(t2);
+            if tmin > tmax {
+                return None;
+            }
+        }
+        // the box lies entirely behind the ray origin
+        if tmax < -FLOAT_EPS {
+            return None;
+        }
+        Some(tmin)
+    }
+}
+
+fn component_min(a: Point, b: Point) -> Point {
+    point!(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+}
+
+fn component_max(a: Point, b: Point) -> Point {
+    point!(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+}
+
+enum BvhNode {
+    Leaf { bbox: Aabb, indices: Vec<usize> },
+    Internal { bbox: Aabb, left: Box<BvhNode>, right: Box<BvhNode> },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Internal { bbox, .. } => bbox,
+        }
+    }
+
+    // splits `entries` by sorting on the centroid along the box's longest axis
+    // and partitioning at the median
+    fn build(entries: &mut [(usize, Aabb)]) -> BvhNode {
+        let bbox = entries[1..].iter()
+            .fold(entries[0].1, |acc, (_, b)| acc.union(b));
+        if entries.len() <= LEAF_SIZE {
+            return BvhNode::Leaf { bbox, indices: entries.iter().map(|(i, _)| *i).collect() };
+        }
+        let extent = vector!(bbox.min, bbox.max);
+        let axis = (0..3usize)
+            .max_by(|&a, &b| extent[a].partial_cmp(&extent[b]).unwrap())
+            .unwrap();
+        entries.sort_by(|a, b| a.1.centroid()[axis].partial_cmp(&b.1.centroid()[axis]).unwrap());
+        let mid = entries.len() / 2;
+        let (left_entries, right_entries) = entries.split_at_mut(mid);
+        let left = Box::new(BvhNode::build(left_entries));
+        let right = Box::new(BvhNode::build(right_entries));
+        BvhNode::Internal { bbox, left, right }
+    }
+}
+
+pub struct Bvh {
+    root: BvhNode,
+}
+
+impl Bvh {
+    pub fn build(surfaces: &[ColoredSurface]) -> Bvh {
+        let mut entries: Vec<(usize, Aabb)> = surfaces.iter().enumerate()
+            .map(|(i, sf)| (i, Aabb::from_surface(sf.surface.as_ref())))
+            .collect();
+        Bvh { root: BvhNode::build(&mut entries) }
+    }
+
+    // closest surface hit by `ray`, if any
+    pub fn closest_hit(&self, surfaces: &[ColoredSurface], ray: &Line) -> Option<(usize, f64)> {
+        Self::traverse(&self.root, surfaces, ray, |_| true)
+    }
+
+    // whether `ray` hits any surface, ignoring surfaces containing `pt`; hits are not
+    // limited to the distance of the light
+    pub fn any_blocking(&self, surfaces: &[ColoredSurface], ray: &Line, pt: Point) -> bool {
+        Self::traverse(&self.root, surfaces, ray, |i| !surfaces[i].surface.contains(pt))
+            .is_some()
+    }
+
+    fn traverse(node: &BvhNode, surfaces: &[ColoredSurface], ray: &Line,
+                accept: impl Fn(usize) -> bool + Copy) -> Option<(usize, f64)> {
+        node.bbox().intersect(ray)?;
+        match node {
+            BvhNode::Leaf { indices, .. } => {
+                indices.iter().copied()
+                    .filter(|&i| accept(i))
+                    .filter_map(|i| surfaces[i].surface.intersect(ray).map(|t| (i, t)))
+                    // check if it lies on the positive direction of the ray
+                    .filter(|(_, t)| *t >= -FLOAT_EPS)
+                    // find closest to the origin
+                    .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            }
+            BvhNode::Internal { left, right, .. } => {
+                let left_t = left.bbox().intersect(ray);
+                let right_t = right.bbox().intersect(ray);
+                // descend the nearer child first
+                let (first, second) = match (left_t, right_t) {
+                    (Some(lt), Some(rt)) if rt < lt => (right.as_ref(), left.as_ref()),
+                    _ => (left.as_ref(), right.as_ref()),
+                };
+                match (Self::traverse(first, surfaces, ray, accept),
+                       Self::traverse(second, surfaces, ray, accept)) {
+                    (Some(a), Some(b)) => Some(if a.1 <= b.1 { a } else { b }),
+                    (Some(a), None) => Some(a),
+                    (None, Some(b)) => Some(b),
+                    (None, None) => None,
+                }
+            }
+        }
+    }
+}
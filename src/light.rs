@@ -0,0 +1,63 @@
+use super::*;
+use super::vector::*;
+
+// common interface for anything that can illuminate a point in the scene
+pub trait Light: Send + Sync {
+    // unit direction from `pt` toward the light
+    fn direction(&self, pt: Point) -> Vector;
+    // light intensity reaching `pt`, before occlusion
+    fn intensity(&self, pt: Point) -> f32;
+}
+
+// the original single-sun behavior: a constant direction, as if the light sat infinitely far away
+pub struct DirectionalLight {
+    pub sun: Point,
+    pub intensity: f32,
+}
+
+impl Light for DirectionalLight {
+    fn direction(&self, _pt: Point) -> Vector {
+        self.sun.normalized()
+    }
+    fn intensity(&self, _pt: Point) -> f32 {
+        self.intensity
+    }
+}
+
+pub struct PointLight {
+    pub position: Point,
+    pub intensity: f32,
+    pub falloff: f32,
+}
+
+impl Light for PointLight {
+    fn direction(&self, pt: Point) -> Vector {
+        vector!(pt, self.position).normalized()
+    }
+    fn intensity(&self, pt: Point) -> f32 {
+        let dist_sq = vector!(pt, self.position).dot(vector!(pt, self.position));
+        self.intensity / (1.0 + self.falloff * dist_sq as f32)
+    }
+}
+
+pub struct SpotLight {
+    pub position: Point,
+    pub direction: Vector,
+    pub cone_angle: f64,
+    pub intensity: f32,
+}
+
+impl Light for SpotLight {
+    fn direction(&self, pt: Point) -> Vector {
+        vector!(pt, self.position).normalized()
+    }
+    fn intensity(&self, pt: Point) -> f32 {
+        let to_point = vector!(self.position, pt).normalized();
+        let cos_angle = self.direction.normalized().dot(to_point);
+        if cos_angle >= self.cone_angle.cos() {
+            self.intensity
+        } else {
+            0.0
+        }
+    }
+}
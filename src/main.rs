@@ -8,6 +8,9 @@ mod vector;
 mod line;
 mod surface;
 mod shapes;
+mod bvh;
+mod light;
+mod transform;
 
 use std::path::Path;
 use std::fs::File;
@@ -19,8 +22,11 @@ use scoped_threadpool::Pool;
 
 use vector::{Point, Vector};
 use line::Line;
-use surface::{Triangle};
+use surface::Surface;
 use shapes::*;
+use bvh::Bvh;
+use light::{Light, DirectionalLight};
+use transform::{Matrix4, Camera, Instance};
 
 // ========================== Float & Wrapper ======================================================
 
@@ -30,6 +36,15 @@ pub fn is_zero(f: f64) -> bool {
     f.abs() <= FLOAT_EPS
 }
 
+// cheap deterministic pseudo-random hash in [0, 1), used to jitter AA samples without a rng crate
+fn jitter(seed: u64) -> f64 {
+    let mut x = seed.wrapping_mul(0x9E3779B97F4A7C15).wrapping_add(0x2545F4914F6CDD1D);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51afd7ed558ccd);
+    x ^= x >> 33;
+    (x % 1_000_000) as f64 / 1_000_000.0
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum MathError {
     CollinearVectors
@@ -42,17 +57,24 @@ pub type MathResult<T> = Result<T, MathError>;
 type Color = [u8; 3];
 
 struct ColoredSurface {
-    triangle: Triangle,
+    surface: Box<dyn Surface>,
     color: Color,
+    specular: f32,
+    shininess: f32,
+    reflectivity: f32,
 }
 
 struct Environment {
-    origin: Vector,
-    sun: Vector,
+    camera: Camera,
+    lights: Vec<Box<dyn Light>>,
     ambient_light: f32,
     diffuse_light: f32,
+    ks: f32,
     grid_size: f64,
+    aa_samples: u32,
+    max_depth: u32,
     surfaces: Vec<ColoredSurface>,
+    bvh: Bvh,
 }
 
 const IMAGE_SIZE: (u32, u32) = (500, 500);
@@ -60,57 +82,82 @@ const VOID_COLOR: [u8; 3] = [30, 30, 30];
 
 // ========================== Ray casting ==========================================================
 
-fn compute_lights(env: &Environment, surface: &Triangle, pt: Point) -> f32 {
-    let sun_ray = Line {
-        direction: vector!(pt, env.sun),
-        origin: pt,
-    };
-    let covered = env.surfaces.iter()
-        .filter(|sf| !sf.triangle.contains(pt))
-        .map(|sf| sf.triangle.intersect(&sun_ray))
-        // check if any intersection lies on the positive direction of the ray
-        .any(|opt| opt.map(|t| t >= -FLOAT_EPS).unwrap_or(false));
-    let different_halves = surface.plane.subs(env.origin)
-        * surface.plane.subs(env.sun) <= 0.0;
-    if covered || different_halves {
-        env.ambient_light
-    } else {
-        let normal = surface.plane.normal();
-        let cos = sun_ray.direction.cos(normal).abs() as f32;
-        (1.0 - env.diffuse_light) + cos * env.diffuse_light
+fn compute_lights(env: &Environment, surface: &ColoredSurface, pt: Point) -> f32 {
+    let raw_normal = surface.surface.normal(pt);
+    let to_origin = vector!(pt, env.camera.eye);
+    let v = to_origin.normalized();
+    // flip the normal toward the viewer so the highlight lands on the lit side
+    let mut normal = raw_normal.normalized();
+    if normal.dot(v) < 0.0 {
+        normal = normal * -1.0;
     }
-}
 
-fn cast_ray(env: &Environment, ray: &Line) -> [u8; 3] {
-    let intersection_opt = env.surfaces.iter()
-        .map(|sf: &ColoredSurface| sf.triangle.intersect(ray).map(|t| (t, sf)))
-        .filter(Option::is_some).map(Option::unwrap)
-        // check if it lies on the positive direction of the ray
-        .filter(|is| is.0 >= -FLOAT_EPS)
-        // find closest to the origin
-        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-    if let Some((ray_param, surface)) = intersection_opt {
-        let brightness = compute_lights(&env, &surface.triangle, ray.at(ray_param));
-        surface.color.iter()
-            .map(|c| (*c as f32 * brightness) as u8).try_collect().unwrap()
-    } else {
-        VOID_COLOR
+    let mut brightness = env.ambient_light;
+    for light in &env.lights {
+        let l = light.direction(pt);
+        let shadow_ray = Line { direction: l, origin: pt };
+        let covered = env.bvh.any_blocking(&env.surfaces, &shadow_ray, pt);
+        let different_halves = raw_normal.dot(to_origin) * raw_normal.dot(l) <= 0.0;
+        if covered || different_halves {
+            continue;
+        }
+        let intensity = light.intensity(pt);
+        let cos = normal.dot(l).max(0.0) as f32;
+        let spec = l.reflect(normal).dot(v).max(0.0).powf(surface.shininess as f64) as f32;
+        brightness += intensity * (env.diffuse_light * cos + env.ks * surface.specular * spec);
     }
+    brightness.min(1.0)
 }
 
-fn create_ray(env: &Environment, (x, y): (u32, u32)) -> Line {
-    let interpolated = |cur: u32, max: u32| -> f64 {
-        2f64 * (cur as f64 / max as f64) - 1f64
+fn cast_ray(env: &Environment, ray: &Line, depth: u32) -> [u8; 3] {
+    let (idx, ray_param) = match env.bvh.closest_hit(&env.surfaces, ray) {
+        Some(hit) => hit,
+        None => return VOID_COLOR,
     };
-    let vx = vector!(cross env.origin, vector!(axis y)).normalized();
-    let vy = vector!(cross env.origin, vx).normalized();
-    let pt = vector!()
-        + interpolated(y, IMAGE_SIZE.1) * env.grid_size * vx
-        + interpolated(x, IMAGE_SIZE.0) * env.grid_size * vy;
-    Line {
-        direction: vector!(env.origin, pt),
-        origin: env.origin,
+    let surface = &env.surfaces[idx];
+    let hit_pt = ray.at(ray_param);
+    let brightness = compute_lights(&env, surface, hit_pt);
+    let local_color: [f32; 3] = surface.color.iter()
+        .map(|c| *c as f32 * brightness).try_collect().unwrap();
+
+    if surface.reflectivity <= 0.0 || depth == 0 {
+        return local_color.iter().map(|c| *c as u8).try_collect().unwrap();
+    }
+
+    // flip the normal toward the incoming ray so it points out of the surface
+    let d = ray.direction.normalized();
+    let mut normal = surface.surface.normal(hit_pt).normalized();
+    if normal.dot(d) > 0.0 {
+        normal = normal * -1.0;
     }
+    let reflected_dir = d.reflect(normal) * -1.0;
+    let reflected_ray = Line {
+        direction: reflected_dir,
+        // offset along the normal to avoid immediately re-hitting the same surface
+        origin: hit_pt + normal * FLOAT_EPS * 1e3,
+    };
+    let reflected_color = cast_ray(env, &reflected_ray, depth - 1);
+
+    local_color.iter().zip(reflected_color.iter())
+        .map(|(local, reflected)| {
+            ((1.0 - surface.reflectivity) * local + surface.reflectivity * *reflected as f32) as u8
+        }).try_collect().unwrap()
+}
+
+// sub-pixel ray for sample (sx, sy) of the pixel's NxN jittered sample grid
+fn create_ray(env: &Environment, (x, y): (u32, u32), (sx, sy): (u32, u32)) -> Line {
+    let n = env.aa_samples as f64;
+    let ox = jitter(((x as u64) << 32) | ((y as u64) << 16) | ((sx as u64) << 8) | (sy as u64));
+    let oy = jitter(((y as u64) << 32) | ((x as u64) << 16) | ((sy as u64) << 8) | (sx as u64));
+    let cx = x as f64 + (sx as f64 + ox) / n;
+    let cy = y as f64 + (sy as f64 + oy) / n;
+    let interpolated = |cur: f64, max: u32| -> f64 {
+        2f64 * (cur / max as f64) - 1f64
+    };
+    env.camera.primary_ray(
+        interpolated(cy, IMAGE_SIZE.1) * env.grid_size,
+        interpolated(cx, IMAGE_SIZE.0) * env.grid_size,
+    )
 }
 
 fn cast_rays(env: &Environment, pool: &mut Pool) -> Vec<u8> {
@@ -122,12 +169,24 @@ fn cast_rays(env: &Environment, pool: &mut Pool) -> Vec<u8> {
         for chunk in buff.chunks_mut(chunks_size) {
             let chunk_len = chunk.len();
             scope.execute(move || {
-                let rays = (0..chunk.len() as u32)
+                let pixels = (0..chunk.len() as u32)
                     .map(|i| i + offset)
-                    .map(|i| (i / IMAGE_SIZE.1, i % IMAGE_SIZE.1))
-                    .map(|cords| create_ray(&env, cords));
-                for (pixel, ray) in chunk.iter_mut().zip(rays) {
-                    *pixel = cast_ray(&env, &ray);
+                    .map(|i| (i / IMAGE_SIZE.1, i % IMAGE_SIZE.1));
+                for (pixel, cords) in chunk.iter_mut().zip(pixels) {
+                    let mut accum = [0f32; 3];
+                    for sx in 0..env.aa_samples {
+                        for sy in 0..env.aa_samples {
+                            let ray = create_ray(&env, cords, (sx, sy));
+                            let sample = cast_ray(&env, &ray, env.max_depth);
+                            for c in 0..3 {
+                                accum[c] += sample[c] as f32;
+                            }
+                        }
+                    }
+                    let total = (env.aa_samples * env.aa_samples) as f32;
+                    for c in 0..3 {
+                        pixel[c] = (accum[c] / total) as u8;
+                    }
                 }
             });
             offset += chunk_len as u32;
@@ -171,8 +230,11 @@ fn parse_wavefront(filename: &str) -> Vec<ColoredSurface> {
         match triangle(points[0], points[1], points[2]) {
             Ok(triangle) => {
                 out.push(ColoredSurface {
-                    triangle,
+                    surface: Box::new(triangle),
                     color,
+                    specular: 0.3,
+                    shininess: 32.0,
+                    reflectivity: 0.0,
                 })
             }
             Err(err) => {
@@ -185,20 +247,42 @@ fn parse_wavefront(filename: &str) -> Vec<ColoredSurface> {
         let size = 60.0;
         let (tri1, tri2) = plane(point!(0, min_y - 2.0 * FLOAT_EPS, 0), size, size)
             .unwrap();
-        out.push(ColoredSurface { triangle: tri1, color: [200, 200, 200] });
-        out.push(ColoredSurface { triangle: tri2, color: [200, 200, 200] });
+        out.push(ColoredSurface { surface: Box::new(tri1), color: [200, 200, 200], specular: 0.1, shininess: 8.0, reflectivity: 0.4 });
+        out.push(ColoredSurface { surface: Box::new(tri2), color: [200, 200, 200], specular: 0.1, shininess: 8.0, reflectivity: 0.4 });
     }
     out
 }
 
 fn main() {
+    let mut surfaces = parse_wavefront("test/tower.obj");
+    // instance the same cube mesh at a few positions and orientations around the tower
+    for (i, offset) in [vector!(40, -5, 40), vector!(-40, -5, 40), vector!(40, -5, -40)].iter().enumerate() {
+        let transform = Matrix4::translate(*offset) * Matrix4::rotate(vector!(axis y), i as f64 * 0.7);
+        for tri in cube(point!(0, 0, 0), 8.0).unwrap() {
+            surfaces.push(ColoredSurface {
+                surface: Box::new(Instance::new(Box::new(tri), transform)),
+                color: [80, 180, 220],
+                specular: 0.4,
+                shininess: 24.0,
+                reflectivity: 0.0,
+            });
+        }
+    }
+    let bvh = Bvh::build(&surfaces);
+    let lights: Vec<Box<dyn Light>> = vec![
+        Box::new(DirectionalLight { sun: vector!(-80, 150, 80), intensity: 1.0 }),
+    ];
     let mut env = Environment {
-        origin: vector!(-5, 70, 0),
-        sun: vector!(-80, 150, 80),
+        camera: Camera::look_at(vector!(-5, 70, 0), vector!(0, 0, 0), vector!(axis y)),
+        lights,
         ambient_light: 0.4,
         diffuse_light: 0.2,
+        ks: 0.5,
         grid_size: 40.0,
-        surfaces: parse_wavefront("test/tower.obj"),
+        aa_samples: 2,
+        max_depth: 4,
+        surfaces,
+        bvh,
     };
     let mut thread_pool = Pool::new(num_cpus::get() as u32);
     let origin_radius = 160f64;
@@ -207,8 +291,8 @@ fn main() {
     for step in 0..20 {
         let percent = step as f64 / steps as f64;
         let angle: f64 = percent * 2.0 * std::f64::consts::PI;
-        env.origin.x = angle.sin() * origin_radius;
-        env.origin.z = angle.cos() * origin_radius;
+        let eye = vector!(angle.sin() * origin_radius, 70.0, angle.cos() * origin_radius);
+        env.camera = Camera::look_at(eye, vector!(0, 0, 0), vector!(axis y));
 
         let buffer = cast_rays(&env, &mut thread_pool);
         image::save_buffer(&Path::new(&format!("test/output{}.png", step)),
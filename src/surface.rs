@@ -90,4 +90,83 @@ impl Triangle {
                 }
             }).is_some()
     }
+}
+
+// ========================== Surface trait =========================================================
+
+// common interface for anything a ray can hit, so scenes can mix meshes with analytic primitives
+pub trait Surface: Send + Sync {
+    fn intersect(&self, line: &Line) -> Option<f64>;
+    fn normal(&self, pt: Point) -> Vector;
+    fn contains(&self, pt: Point) -> bool;
+    // axis-aligned bounding box as (min, max), used by the BVH
+    fn bounds(&self) -> (Point, Point);
+}
+
+impl Surface for Triangle {
+    fn intersect(&self, line: &Line) -> Option<f64> {
+        Triangle::intersect(self, line)
+    }
+    fn normal(&self, _pt: Point) -> Vector {
+        self.plane.normal()
+    }
+    fn contains(&self, pt: Point) -> bool {
+        Triangle::contains(self, pt)
+    }
+    fn bounds(&self) -> (Point, Point) {
+        let mut min = self.vertices[0];
+        let mut max = self.vertices[0];
+        for v in &self.vertices[1..] {
+            min = point!(min.x.min(v.x), min.y.min(v.y), min.z.min(v.z));
+            max = point!(max.x.max(v.x), max.y.max(v.y), max.z.max(v.z));
+        }
+        (min, max)
+    }
+}
+
+#[derive(Debug)]
+pub struct Sphere {
+    pub center: Point,
+    pub radius: f64,
+}
+
+impl Sphere {
+    pub fn new(center: Point, radius: f64) -> Self {
+        Sphere { center, radius }
+    }
+}
+
+impl Surface for Sphere {
+    fn intersect(&self, line: &Line) -> Option<f64> {
+        let oc = vector!(self.center, line.origin);
+        let a = line.direction.dot(line.direction);
+        let b = 2.0 * oc.dot(line.direction);
+        let c = oc.dot(oc) - self.radius * self.radius;
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt_discriminant = discriminant.sqrt();
+        let t1 = (-b - sqrt_discriminant) / (2.0 * a);
+        let t2 = (-b + sqrt_discriminant) / (2.0 * a);
+        // smallest root that still lies on the positive direction of the ray
+        if t1 > -FLOAT_EPS {
+            Some(t1)
+        } else if t2 > -FLOAT_EPS {
+            Some(t2)
+        } else {
+            None
+        }
+    }
+    fn normal(&self, pt: Point) -> Vector {
+        vector!(self.center, pt).normalized()
+    }
+    fn contains(&self, pt: Point) -> bool {
+        is_zero(vector!(self.center, pt).len() - self.radius)
+    }
+    fn bounds(&self) -> (Point, Point) {
+        let r = self.radius;
+        (point!(self.center.x - r, self.center.y - r, self.center.z - r),
+         point!(self.center.x + r, self.center.y + r, self.center.z + r))
+    }
 }
\ No newline at end of file
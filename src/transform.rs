@@ -0,0 +1,220 @@
+use super::*;
+use super::vector::*;
+use super::line::Line;
+use super::surface::Surface;
+
+// row-major 4x4 affine transform
+#[derive(Copy, Clone, Debug)]
+pub struct Matrix4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Matrix4 {
+        Matrix4 {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        }
+    }
+
+    pub fn translate(v: Vector) -> Matrix4 {
+        let mut out = Matrix4::identity();
+        out.m[0][3] = v.x;
+        out.m[1][3] = v.y;
+        out.m[2][3] = v.z;
+        out
+    }
+
+    pub fn scale(v: Vector) -> Matrix4 {
+        let mut out = Matrix4::identity();
+        out.m[0][0] = v.x;
+        out.m[1][1] = v.y;
+        out.m[2][2] = v.z;
+        out
+    }
+
+    // rotation by `angle` radians about `axis`, via Rodrigues' formula
+    pub fn rotate(axis: Vector, angle: f64) -> Matrix4 {
+        let a = axis.normalized();
+        let (s, c) = (angle.sin(), angle.cos());
+        let t = 1.0 - c;
+        let (x, y, z) = (a.x, a.y, a.z);
+        Matrix4 {
+            m: [
+                [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+                [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+                [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        }
+    }
+
+    pub fn transpose(&self) -> Matrix4 {
+        let mut out = Matrix4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                out.m[i][j] = self.m[j][i];
+            }
+        }
+        out
+    }
+
+    // affine transforms built from translate/scale/rotate always keep the bottom row
+    // [0, 0, 0, 1], so this only ever has to invert the upper-left 3x3 block
+    pub fn inverse(&self) -> Matrix4 {
+        let a = [
+            [self.m[0][0], self.m[0][1], self.m[0][2]],
+            [self.m[1][0], self.m[1][1], self.m[1][2]],
+            [self.m[2][0], self.m[2][1], self.m[2][2]],
+        ];
+        let inv_a = invert3x3(&a);
+        let t = [self.m[0][3], self.m[1][3], self.m[2][3]];
+        let mut inv_t = [0.0; 3];
+        for i in 0..3 {
+            inv_t[i] = -(0..3).map(|j| inv_a[i][j] * t[j]).sum::<f64>();
+        }
+        Matrix4 {
+            m: [
+                [inv_a[0][0], inv_a[0][1], inv_a[0][2], inv_t[0]],
+                [inv_a[1][0], inv_a[1][1], inv_a[1][2], inv_t[1]],
+                [inv_a[2][0], inv_a[2][1], inv_a[2][2], inv_t[2]],
+                [0.0, 0.0, 0.0, 1.0],
+            ]
+        }
+    }
+
+    pub fn transform_point(&self, p: Point) -> Point {
+        let m = &self.m;
+        point!(
+            m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3],
+            m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3],
+            m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3]
+        )
+    }
+
+    // direction vectors ignore translation
+    pub fn transform_vector(&self, v: Vector) -> Vector {
+        let m = &self.m;
+        vector!(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z
+        )
+    }
+}
+
+impl std::ops::Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut out = Matrix4::identity();
+        for i in 0..4 {
+            for j in 0..4 {
+                out.m[i][j] = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        out
+    }
+}
+
+fn invert3x3(a: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let det = a[0][0] * (a[1][1] * a[2][2] - a[1][2] * a[2][1])
+        - a[0][1] * (a[1][0] * a[2][2] - a[1][2] * a[2][0])
+        + a[0][2] * (a[1][0] * a[2][1] - a[1][1] * a[2][0]);
+    let inv_det = 1.0 / det;
+    [
+        [(a[1][1] * a[2][2] - a[1][2] * a[2][1]) * inv_det,
+         (a[0][2] * a[2][1] - a[0][1] * a[2][2]) * inv_det,
+         (a[0][1] * a[1][2] - a[0][2] * a[1][1]) * inv_det],
+        [(a[1][2] * a[2][0] - a[1][0] * a[2][2]) * inv_det,
+         (a[0][0] * a[2][2] - a[0][2] * a[2][0]) * inv_det,
+         (a[0][2] * a[1][0] - a[0][0] * a[1][2]) * inv_det],
+        [(a[1][0] * a[2][1] - a[1][1] * a[2][0]) * inv_det,
+         (a[0][1] * a[2][0] - a[0][0] * a[2][1]) * inv_det,
+         (a[0][0] * a[1][1] - a[0][1] * a[1][0]) * inv_det],
+    ]
+}
+
+// ========================== Instancing ============================================================
+
+// wraps a surface with a world-space transform, so the same geometry can be placed
+// at several positions and orientations
+pub struct Instance {
+    surface: Box<dyn Surface>,
+    transform: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Instance {
+    pub fn new(surface: Box<dyn Surface>, transform: Matrix4) -> Self {
+        let inverse = transform.inverse();
+        Instance { surface, transform, inverse }
+    }
+}
+
+impl Surface for Instance {
+    fn intersect(&self, line: &Line) -> Option<f64> {
+        let object_ray = Line {
+            origin: self.inverse.transform_point(line.origin),
+            direction: self.inverse.transform_vector(line.direction),
+        };
+        self.surface.intersect(&object_ray)
+    }
+    fn normal(&self, pt: Point) -> Vector {
+        let object_pt = self.inverse.transform_point(pt);
+        let object_normal = self.surface.normal(object_pt);
+        // inverse-transpose keeps normals correct under non-uniform scale
+        self.inverse.transpose().transform_vector(object_normal).normalized()
+    }
+    fn contains(&self, pt: Point) -> bool {
+        self.surface.contains(self.inverse.transform_point(pt))
+    }
+    fn bounds(&self) -> (Point, Point) {
+        let (min, max) = self.surface.bounds();
+        let corners = [
+            point!(min.x, min.y, min.z), point!(max.x, min.y, min.z),
+            point!(min.x, max.y, min.z), point!(min.x, min.y, max.z),
+            point!(max.x, max.y, min.z), point!(max.x, min.y, max.z),
+            point!(min.x, max.y, max.z), point!(max.x, max.y, max.z),
+        ];
+        let mut out_min = self.transform.transform_point(corners[0]);
+        let mut out_max = out_min;
+        for &corner in &corners[1..] {
+            let p = self.transform.transform_point(corner);
+            out_min = point!(out_min.x.min(p.x), out_min.y.min(p.y), out_min.z.min(p.z));
+            out_max = point!(out_max.x.max(p.x), out_max.y.max(p.y), out_max.z.max(p.z));
+        }
+        (out_min, out_max)
+    }
+}
+
+// ========================== Camera ================================================================
+
+pub struct Camera {
+    pub eye: Point,
+    pub target: Point,
+    right: Vector,
+    up: Vector,
+}
+
+impl Camera {
+    pub fn look_at(eye: Point, target: Point, up_hint: Vector) -> Camera {
+        let forward = vector!(eye, target).normalized();
+        let right = up_hint.cross(forward).normalized();
+        let up = right.cross(forward).normalized();
+        Camera { eye, target, right, up }
+    }
+
+    // primary ray through the point `row` units along `right` and `col` units along `up`
+    // from the image plane centered on `target`
+    pub fn primary_ray(&self, row: f64, col: f64) -> Line {
+        let pt = self.target + row * self.right + col * self.up;
+        Line {
+            direction: vector!(self.eye, pt),
+            origin: self.eye,
+        }
+    }
+}
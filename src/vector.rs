@@ -63,6 +63,11 @@ impl Vector {
         return self.is_collinear(v2) && self.x * v2.x >= -FLOAT_EPS
             && self.y * v2.y >= -FLOAT_EPS && self.z * v2.z >= -FLOAT_EPS;
     }
+    // reflects `self` about `normal`, i.e. R = 2*(N.self)*N - self
+    pub fn reflect(&self, normal: Vector) -> Vector {
+        let scaled = normal * (2.0 * self.dot(normal));
+        vector!(scaled.x - self.x, scaled.y - self.y, scaled.z - self.z)
+    }
 }
 
 impl Add<Point> for Point {